@@ -0,0 +1,553 @@
+//! A from-scratch, constant-memory walker over raw CBOR bytes.
+//!
+//! Historically this module only checked the
+//! [RFC 8949 §4.2.1](https://www.rfc-editor.org/rfc/rfc8949#section-4.2.1)
+//! "preferred serialization" (shortest-form) rules for `strict` validation -
+//! not [DAG-CBOR](https://ipld.io/specs/codecs/dag-cbor/spec/)'s stricter
+//! canonical form, which among other differences mandates all floats be
+//! encoded as 64-bit - on the assumption that basic
+//! well-formedness was handled by the `cbor` crate's own decoder. That
+//! decoder materializes the whole value tree up front though
+//! (`Decoder::items().collect::<Vec<_>>()`), which means a hostile blob - a
+//! declared 4 GB string length, or a few bytes of deeply nested arrays - can
+//! exhaust memory or the stack before validation ever gets a chance to
+//! reject it. This module now also backs the ordinary (non-strict) decode
+//! check, tokenizing incrementally (in the style of minicbor's
+//! `Tokenizer`/`Token` iterator) and tracking nesting depth and item counts
+//! against configurable limits as it goes, instead of collecting a
+//! `Vec<Cbor>`.
+
+use half::f16;
+
+/// A single rule violation, with a human readable reason and the byte
+/// offset at which it was detected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub offset: usize,
+    pub reason: String,
+}
+
+impl Violation {
+    fn new(offset: usize, reason: impl Into<String>) -> Self {
+        Violation { offset, reason: reason.into() }
+    }
+}
+
+/// Which configured limit was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Depth,
+    CollectionItems,
+    TotalItems,
+    StringBytes,
+}
+
+/// The result of walking a CBOR blob: either it is malformed (not valid CBOR
+/// at all), it violates the canonical encoding rules (only checked in
+/// `strict` mode), or it exceeds a configured resource limit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalkError {
+    Malformed(Violation),
+    NonCanonical(Violation),
+    LimitExceeded(Limit),
+}
+
+/// Resource bounds enforced while walking untrusted CBOR input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum array/map/tag/chunked-string nesting depth.
+    pub max_depth: usize,
+    /// Maximum number of items (array elements, map entries, string chunks)
+    /// a single collection may declare.
+    pub max_collection_items: u64,
+    /// Maximum number of items across the entire input.
+    pub max_total_items: u64,
+    /// Maximum length, in bytes, of a single byte/text string (or, for an
+    /// indefinite-length string, of a single chunk of one).
+    pub max_string_bytes: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 128,
+            max_collection_items: 10_000,
+            max_total_items: 1_000_000,
+            max_string_bytes: 10_000_000,
+        }
+    }
+}
+
+/// Walks `data` as a sequence of back-to-back top-level CBOR items (mirroring
+/// `cbor::Decoder::items()`), checking well-formedness and resource limits,
+/// and - when `strict` is set - the RFC 8949 preferred/shortest-form
+/// serialization rules.
+pub fn walk(data: &[u8], limits: &Limits, strict: bool) -> Result<(), WalkError> {
+    let mut walker = Walker { buf: data, pos: 0, limits, strict, depth: 0, total_items: 0 };
+    while walker.pos < walker.buf.len() {
+        walker.item()?;
+    }
+    Ok(())
+}
+
+/// Convenience wrapper for the common case of just checking canonicality
+/// against the default resource limits.
+pub fn check(data: &[u8]) -> Result<(), Violation> {
+    match walk(data, &Limits::default(), true) {
+        Ok(()) => Ok(()),
+        Err(WalkError::NonCanonical(violation)) | Err(WalkError::Malformed(violation)) => Err(violation),
+        Err(WalkError::LimitExceeded(limit)) => Err(Violation::new(0, format!("{:?} limit exceeded", limit))),
+    }
+}
+
+struct Walker<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    limits: &'a Limits,
+    strict: bool,
+    depth: usize,
+    total_items: u64,
+}
+
+impl<'a> Walker<'a> {
+    fn item(&mut self) -> Result<(), WalkError> {
+        self.total_items += 1;
+        if self.total_items > self.limits.max_total_items {
+            return Err(WalkError::LimitExceeded(Limit::TotalItems));
+        }
+
+        let start = self.pos;
+        let head = self.read_u8(start)?;
+        let major = head >> 5;
+        let info = head & 0x1f;
+
+        if major == 7 {
+            return self.major_seven(start, info);
+        }
+
+        let value = self.read_argument(start, info)?;
+        if self.strict {
+            check_minimal_length(start, info, value)?;
+        }
+
+        match major {
+            0 | 1 => Ok(()), // unsigned/negative int: nothing further to read
+            2 | 3 => self.string(start, major, info, value),
+            4 => self.array(start, info, value),
+            5 => self.map(start, info, value),
+            6 => self.descend(|w| w.item()), // the tagged value
+            _ => unreachable!("major type out of range"),
+        }
+    }
+
+    fn string(&mut self, start: usize, major: u8, info: u8, value: u64) -> Result<(), WalkError> {
+        if info == 31 {
+            if self.strict {
+                return Err(WalkError::NonCanonical(Violation::new(start, "indefinite-length byte/text string")));
+            }
+            return self.descend(|w| w.indefinite_string_chunks(start, major));
+        }
+        self.check_string_size(value)?;
+        self.take(start, value as usize)?;
+        Ok(())
+    }
+
+    fn indefinite_string_chunks(&mut self, parent_start: usize, major: u8) -> Result<(), WalkError> {
+        let mut count = 0u64;
+        loop {
+            let chunk_start = self.pos;
+            if self.peek_is_break(chunk_start)? {
+                self.pos += 1;
+                return Ok(());
+            }
+            count += 1;
+            self.check_collection_size(count)?;
+
+            let head = self.read_u8(chunk_start)?;
+            let chunk_major = head >> 5;
+            let info = head & 0x1f;
+            if chunk_major != major {
+                return Err(WalkError::Malformed(Violation::new(
+                    chunk_start,
+                    "indefinite-length string chunk has a different major type than its parent",
+                )));
+            }
+            if info == 31 {
+                return Err(WalkError::Malformed(Violation::new(parent_start, "nested indefinite-length chunk")));
+            }
+            let value = self.read_argument(chunk_start, info)?;
+            self.check_string_size(value)?;
+            self.take(chunk_start, value as usize)?;
+        }
+    }
+
+    fn array(&mut self, start: usize, info: u8, value: u64) -> Result<(), WalkError> {
+        if info == 31 {
+            if self.strict {
+                return Err(WalkError::NonCanonical(Violation::new(start, "indefinite-length array")));
+            }
+            return self.descend(|w| w.indefinite_items());
+        }
+        self.check_collection_size(value)?;
+        self.check_remaining_at_least(start, value)?;
+        self.descend(|w| {
+            for _ in 0..value {
+                w.item()?;
+            }
+            Ok(())
+        })
+    }
+
+    fn indefinite_items(&mut self) -> Result<(), WalkError> {
+        let mut count = 0u64;
+        loop {
+            if self.peek_is_break(self.pos)? {
+                self.pos += 1;
+                return Ok(());
+            }
+            count += 1;
+            self.check_collection_size(count)?;
+            self.item()?;
+        }
+    }
+
+    fn map(&mut self, start: usize, info: u8, value: u64) -> Result<(), WalkError> {
+        if info == 31 {
+            if self.strict {
+                return Err(WalkError::NonCanonical(Violation::new(start, "indefinite-length map")));
+            }
+            return self.descend(|w| {
+                let mut count = 0u64;
+                loop {
+                    if w.peek_is_break(w.pos)? {
+                        w.pos += 1;
+                        return Ok(());
+                    }
+                    count += 1;
+                    w.check_collection_size(count)?;
+                    w.item()?; // key
+                    w.item()?; // value
+                }
+            });
+        }
+        self.check_collection_size(value)?;
+        self.check_remaining_at_least(start, value.saturating_mul(2))?;
+        self.descend(|w| w.map_entries(start, value))
+    }
+
+    fn map_entries(&mut self, map_start: usize, len: u64) -> Result<(), WalkError> {
+        let mut prev_key: Option<Vec<u8>> = None;
+        for _ in 0..len {
+            let key_start = self.pos;
+            self.item()?;
+            if self.strict {
+                let key_bytes = &self.buf[key_start..self.pos];
+                if let Some(prev) = &prev_key {
+                    if key_bytes <= prev.as_slice() {
+                        return Err(WalkError::NonCanonical(Violation::new(
+                            map_start,
+                            "map keys are not sorted in strictly ascending bytewise order",
+                        )));
+                    }
+                }
+                prev_key = Some(key_bytes.to_vec());
+            }
+            self.item()?; // value
+        }
+        Ok(())
+    }
+
+    fn major_seven(&mut self, start: usize, info: u8) -> Result<(), WalkError> {
+        match info {
+            0..=19 => Ok(()), // unassigned simple values, encoded directly
+            20..=23 => Ok(()), // false, true, null, undefined
+            24 => {
+                let value = self.read_u8(start)? as u64;
+                // RFC 8949 §3.3: simple values 0..=31 must be encoded
+                // directly in the initial byte; using the one-byte extension
+                // for one of them isn't a canonicalization nit, it's
+                // malformed CBOR, so this is rejected in both modes.
+                if value < 32 {
+                    return Err(WalkError::Malformed(Violation::new(start, "simple value 0..=31 must not use the one-byte encoding")));
+                }
+                Ok(())
+            }
+            25 => {
+                let bits = self.read_u16(start)?;
+                let _ = f16::from_bits(bits);
+                Ok(())
+            }
+            26 => {
+                let bits = self.read_u32(start)?;
+                if self.strict {
+                    let value = f32::from_bits(bits);
+                    if fits_in_f16(value) {
+                        return Err(WalkError::NonCanonical(Violation::new(start, "float32 could be represented as float16")));
+                    }
+                }
+                Ok(())
+            }
+            27 => {
+                let bits = self.read_u64(start)?;
+                if self.strict {
+                    let value = f64::from_bits(bits);
+                    if fits_in_f32(value) {
+                        return Err(WalkError::NonCanonical(Violation::new(start, "float64 could be represented as float32 or smaller")));
+                    }
+                }
+                Ok(())
+            }
+            31 => Err(WalkError::Malformed(Violation::new(start, "unexpected CBOR break outside of an indefinite-length item"))),
+            _ => Err(WalkError::Malformed(Violation::new(start, "reserved additional info for major type 7"))),
+        }
+    }
+
+    /// Runs `f` one nesting level deeper, rejecting input that nests past
+    /// `max_depth` before recursing, so a chain of single-byte "array
+    /// containing one array" headers can't blow the stack.
+    fn descend(&mut self, f: impl FnOnce(&mut Self) -> Result<(), WalkError>) -> Result<(), WalkError> {
+        if self.depth >= self.limits.max_depth {
+            return Err(WalkError::LimitExceeded(Limit::Depth));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn check_collection_size(&self, value: u64) -> Result<(), WalkError> {
+        if value > self.limits.max_collection_items {
+            return Err(WalkError::LimitExceeded(Limit::CollectionItems));
+        }
+        Ok(())
+    }
+
+    /// Checks a declared byte/text string length against `max_string_bytes`.
+    /// This is a byte-length bound, distinct from [`check_collection_size`]'s
+    /// item-count bound - `take` already rejects a declared length that
+    /// outruns the remaining input, so this exists purely to cap how much of
+    /// a large-but-valid input a single string is allowed to claim.
+    fn check_string_size(&self, value: u64) -> Result<(), WalkError> {
+        if value > self.limits.max_string_bytes {
+            return Err(WalkError::LimitExceeded(Limit::StringBytes));
+        }
+        Ok(())
+    }
+
+    /// Rejects a declared element count that couldn't possibly be backed by
+    /// the bytes actually remaining (each element needs at least one byte),
+    /// so a tiny blob can't claim billions of array entries.
+    fn check_remaining_at_least(&self, offset: usize, min_bytes: u64) -> Result<(), WalkError> {
+        let remaining = (self.buf.len() - self.pos) as u64;
+        if min_bytes > remaining {
+            return Err(WalkError::Malformed(Violation::new(offset, "declared length exceeds remaining input")));
+        }
+        Ok(())
+    }
+
+    fn peek_is_break(&self, offset: usize) -> Result<bool, WalkError> {
+        match self.buf.get(offset) {
+            Some(&byte) => Ok(byte == 0xff),
+            None => Err(WalkError::Malformed(Violation::new(offset, "unexpected end of input"))),
+        }
+    }
+
+    fn read_argument(&mut self, head_offset: usize, info: u8) -> Result<u64, WalkError> {
+        match info {
+            0..=23 => Ok(info as u64),
+            24 => Ok(self.read_u8(head_offset)? as u64),
+            25 => Ok(self.read_u16(head_offset)? as u64),
+            26 => Ok(self.read_u32(head_offset)? as u64),
+            27 => self.read_u64(head_offset),
+            28..=30 => Err(WalkError::Malformed(Violation::new(head_offset, "reserved additional info"))),
+            31 => Ok(0), // indefinite length marker; caller decides whether that's allowed
+            _ => unreachable!("additional info out of range"),
+        }
+    }
+
+    fn take(&mut self, offset: usize, len: usize) -> Result<&'a [u8], WalkError> {
+        let end = self.pos.checked_add(len).ok_or_else(|| WalkError::Malformed(Violation::new(offset, "length overflow")))?;
+        if end > self.buf.len() {
+            return Err(WalkError::Malformed(Violation::new(offset, "declared length exceeds remaining input")));
+        }
+        let slice = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn read_u8(&mut self, offset: usize) -> Result<u8, WalkError> {
+        Ok(self.take(offset, 1)?[0])
+    }
+
+    fn read_u16(&mut self, offset: usize) -> Result<u16, WalkError> {
+        let bytes = self.take(offset, 2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self, offset: usize) -> Result<u32, WalkError> {
+        let bytes = self.take(offset, 4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_u64(&mut self, offset: usize) -> Result<u64, WalkError> {
+        let bytes = self.take(offset, 8)?;
+        let mut array = [0u8; 8];
+        array.copy_from_slice(bytes);
+        Ok(u64::from_be_bytes(array))
+    }
+}
+
+fn fits_in_f16(value: f32) -> bool {
+    if value.is_nan() {
+        return false;
+    }
+    let narrowed = f16::from_f32(value);
+    narrowed.to_f32() == value
+}
+
+fn fits_in_f32(value: f64) -> bool {
+    if value.is_nan() {
+        return false;
+    }
+    let narrowed = value as f32;
+    (narrowed as f64) == value
+}
+
+/// Computes the additional-info nibble that a canonical encoder would use for
+/// `value`, so it can be compared against the additional info actually read.
+fn minimal_info_for(value: u64) -> u8 {
+    if value < 24 {
+        value as u8
+    } else if value <= u8::MAX as u64 {
+        24
+    } else if value <= u16::MAX as u64 {
+        25
+    } else if value <= u32::MAX as u64 {
+        26
+    } else {
+        27
+    }
+}
+
+fn check_minimal_length(offset: usize, info: u8, value: u64) -> Result<(), WalkError> {
+    if info == 31 {
+        // indefinite length, handled (and rejected) by the caller
+        return Ok(());
+    }
+    let expected = minimal_info_for(value);
+    if expected != info {
+        return Err(WalkError::NonCanonical(Violation::new(
+            offset,
+            format!("length/value {} not encoded in its shortest form", value),
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn canonical_true_is_ok() {
+        assert!(check(&hex!("f5")).is_ok());
+    }
+
+    #[test]
+    fn non_minimal_uint_is_rejected() {
+        // 0 encoded as a 1-byte-follows unsigned int instead of directly
+        let violation = check(&hex!("1800")).unwrap_err();
+        assert!(violation.reason.contains("shortest form"));
+    }
+
+    #[test]
+    fn indefinite_length_array_is_rejected_in_strict_mode() {
+        // array(*) [1, break]
+        let violation = check(&hex!("9f01ff")).unwrap_err();
+        assert!(violation.reason.contains("indefinite-length array"));
+    }
+
+    #[test]
+    fn indefinite_length_array_is_ok_outside_strict_mode() {
+        assert!(walk(&hex!("9f01ff"), &Limits::default(), false).is_ok());
+    }
+
+    #[test]
+    fn indefinite_string_chunk_with_mismatched_major_type_is_rejected() {
+        // indefinite byte string (5f) whose only "chunk" is a uint (00), then break
+        assert!(matches!(walk(&hex!("5f00ff"), &Limits::default(), false), Err(WalkError::Malformed(_))));
+    }
+
+    #[test]
+    fn one_byte_simple_value_under_32_is_rejected_even_outside_strict_mode() {
+        // major 7, additional info 24 (one-byte simple value), value 0
+        assert!(matches!(walk(&hex!("f800"), &Limits::default(), false), Err(WalkError::Malformed(_))));
+    }
+
+    #[test]
+    fn unsorted_map_keys_are_rejected() {
+        // {"b": 1, "a": 2}
+        let data = hex!("a2616201616102");
+        let violation = check(&data).unwrap_err();
+        assert!(violation.reason.contains("sorted"));
+    }
+
+    #[test]
+    fn duplicate_map_keys_are_rejected() {
+        // {"a": 1, "a": 2}
+        let data = hex!("a2616101616102");
+        let violation = check(&data).unwrap_err();
+        assert!(violation.reason.contains("sorted"));
+    }
+
+    #[test]
+    fn oversized_float_is_rejected() {
+        // float64 encoding of 1.0, which fits in float32 (and float16)
+        let data = hex!("fb3ff0000000000000");
+        let violation = check(&data).unwrap_err();
+        assert!(violation.reason.contains("float"));
+    }
+
+    #[test]
+    fn declared_length_longer_than_input_is_rejected() {
+        // byte string claiming 4 bytes but only 1 is present
+        let data = hex!("4401");
+        assert!(matches!(walk(&data, &Limits::default(), false), Err(WalkError::Malformed(_))));
+    }
+
+    #[test]
+    fn nesting_past_max_depth_is_rejected() {
+        // 64 single-element nested arrays: 81 81 81 ... 00
+        let mut data = vec![0x81u8; 64];
+        data.push(0x00);
+        let limits = Limits { max_depth: 8, ..Limits::default() };
+        assert!(matches!(walk(&data, &limits, false), Err(WalkError::LimitExceeded(Limit::Depth))));
+    }
+
+    #[test]
+    fn long_string_is_not_treated_as_an_oversized_collection() {
+        // byte string of 20 bytes, longer than max_collection_items below but
+        // well under the default max_string_bytes
+        let mut data = vec![0x54u8]; // bstr(20)
+        data.extend_from_slice(&[0u8; 20]);
+        let limits = Limits { max_collection_items: 10, ..Limits::default() };
+        assert!(walk(&data, &limits, false).is_ok());
+    }
+
+    #[test]
+    fn oversized_string_is_rejected() {
+        let mut data = vec![0x54u8]; // bstr(20)
+        data.extend_from_slice(&[0u8; 20]);
+        let limits = Limits { max_string_bytes: 10, ..Limits::default() };
+        assert!(matches!(walk(&data, &limits, false), Err(WalkError::LimitExceeded(Limit::StringBytes))));
+    }
+
+    #[test]
+    fn declared_collection_size_over_limit_is_rejected() {
+        // array(1000): 0x99 0x03 0xe8
+        let data = hex!("9903e8");
+        let limits = Limits { max_collection_items: 10, ..Limits::default() };
+        assert!(matches!(walk(&data, &limits, false), Err(WalkError::LimitExceeded(Limit::CollectionItems))));
+    }
+}
@@ -0,0 +1,415 @@
+//! A minimal [CDDL](https://www.rfc-editor.org/rfc/rfc8610) matcher used to
+//! check that the decoded CBOR value of a data property assertion or
+//! annotation conforms to the schema its property declares.
+//!
+//! This is intentionally not a general-purpose CDDL implementation: it only
+//! covers the subset ontology authors need to describe well-typed
+//! assertions — primitive types, arrays, maps, choices, and size/range
+//! controls. Anything outside of that subset is a parse error.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// A minimal decoded CBOR value tree, independent of any particular CBOR
+/// crate's representation, used purely for CDDL matching.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Uint(u64),
+    Nint(i64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+    Float(f64),
+}
+
+/// A compiled CDDL rule.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    Uint,
+    Int,
+    Tstr,
+    Bstr,
+    Bool,
+    Float,
+    Array(Box<Rule>),
+    Map(Box<Rule>, Box<Rule>),
+    Choice(Vec<Rule>),
+    Size(Box<Rule>, u64),
+    Range(Box<Rule>, i64, i64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CddlError {
+    Parse { rule: String, reason: String },
+    Mismatch { reason: String },
+    UnknownProperty,
+    Decode { reason: String },
+}
+
+impl fmt::Display for CddlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CddlError::Parse { rule, reason } => write!(f, "could not parse CDDL rule `{}`: {}", rule, reason),
+            CddlError::Mismatch { reason } => write!(f, "value does not conform to schema: {}", reason),
+            CddlError::UnknownProperty => write!(f, "no CDDL schema registered for property"),
+            CddlError::Decode { reason } => write!(f, "could not decode CBOR value for schema matching: {}", reason),
+        }
+    }
+}
+
+/// Decodes a single CBOR item from `data` into the generic [`Value`] tree
+/// used for CDDL matching.
+pub fn decode(data: &[u8]) -> Result<Value, CddlError> {
+    let mut pos = 0;
+    let value = decode_item(data, &mut pos)?;
+    Ok(value)
+}
+
+fn decode_item(buf: &[u8], pos: &mut usize) -> Result<Value, CddlError> {
+    let head = *buf.get(*pos).ok_or_else(|| CddlError::Decode { reason: "unexpected end of input".into() })?;
+    *pos += 1;
+    let major = head >> 5;
+    let info = head & 0x1f;
+    let arg = decode_argument(buf, pos, info)?;
+
+    match major {
+        0 => Ok(Value::Uint(arg)),
+        1 => {
+            if arg > i64::MAX as u64 {
+                return Err(CddlError::Decode {
+                    reason: format!("negative integer magnitude {} is out of the supported range", arg),
+                });
+            }
+            Ok(Value::Nint(-1 - arg as i64))
+        }
+        2 => Ok(Value::Bytes(take_bytes(buf, pos, arg as usize)?.to_vec())),
+        3 => {
+            let bytes = take_bytes(buf, pos, arg as usize)?;
+            let text = std::str::from_utf8(bytes).map_err(|e| CddlError::Decode { reason: e.to_string() })?;
+            Ok(Value::Text(text.to_string()))
+        }
+        4 => {
+            let items = (0..arg).map(|_| decode_item(buf, pos)).collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::Array(items))
+        }
+        5 => {
+            let entries = (0..arg)
+                .map(|_| Ok((decode_item(buf, pos)?, decode_item(buf, pos)?)))
+                .collect::<Result<Vec<_>, CddlError>>()?;
+            Ok(Value::Map(entries))
+        }
+        6 => decode_item(buf, pos), // tags are transparent for schema matching
+        7 => match info {
+            20 => Ok(Value::Bool(false)),
+            21 => Ok(Value::Bool(true)),
+            22 => Ok(Value::Null),
+            25 => Ok(Value::Float(half::f16::from_bits(arg as u16).to_f64())),
+            26 => Ok(Value::Float(f32::from_bits(arg as u32) as f64)),
+            27 => Ok(Value::Float(f64::from_bits(arg))),
+            other => Err(CddlError::Decode { reason: format!("unsupported simple value {}", other) }),
+        },
+        other => Err(CddlError::Decode { reason: format!("invalid major type {}", other) }),
+    }
+}
+
+fn decode_argument(buf: &[u8], pos: &mut usize, info: u8) -> Result<u64, CddlError> {
+    let read = |buf: &[u8], pos: &mut usize, len: usize| -> Result<u64, CddlError> {
+        let bytes = take_bytes(buf, pos, len)?;
+        let mut array = [0u8; 8];
+        array[8 - len..].copy_from_slice(bytes);
+        Ok(u64::from_be_bytes(array))
+    };
+
+    match info {
+        0..=23 => Ok(info as u64),
+        24 => read(buf, pos, 1),
+        25 => read(buf, pos, 2),
+        26 => read(buf, pos, 4),
+        27 => read(buf, pos, 8),
+        other => Err(CddlError::Decode { reason: format!("unsupported additional info {}", other) }),
+    }
+}
+
+fn take_bytes<'a>(buf: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8], CddlError> {
+    let end = pos.checked_add(len).ok_or_else(|| CddlError::Decode { reason: "length overflow".into() })?;
+    if end > buf.len() {
+        return Err(CddlError::Decode { reason: "declared length exceeds remaining input".into() });
+    }
+    let slice = &buf[*pos..end];
+    *pos = end;
+    Ok(slice)
+}
+
+impl std::error::Error for CddlError {}
+
+/// A registry mapping property identifiers to the CDDL rule their asserted
+/// values must conform to.
+#[derive(Debug, Default)]
+pub struct CddlSchemas {
+    rules: HashMap<Vec<u8>, Rule>,
+}
+
+impl CddlSchemas {
+    pub fn new() -> Self {
+        CddlSchemas { rules: HashMap::new() }
+    }
+
+    /// Parses `cddl_rule` (the right-hand side of a CDDL rule definition,
+    /// e.g. `"uint"` or `"[* tstr]"`) and registers it for `property`.
+    pub fn register(&mut self, property: Vec<u8>, cddl_rule: &str) -> Result<(), CddlError> {
+        let rule = parse_rule(cddl_rule)?;
+        self.rules.insert(property, rule);
+        Ok(())
+    }
+
+    /// Checks `value` against the rule registered for `property`.
+    pub fn validate(&self, property: &[u8], value: &Value) -> Result<(), CddlError> {
+        let rule = self.rules.get(property).ok_or(CddlError::UnknownProperty)?;
+        matches_rule(rule, value)
+    }
+}
+
+fn matches_rule(rule: &Rule, value: &Value) -> Result<(), CddlError> {
+    let ok = match rule {
+        Rule::Uint => matches!(value, Value::Uint(_)),
+        Rule::Int => matches!(value, Value::Uint(_) | Value::Nint(_)),
+        Rule::Tstr => matches!(value, Value::Text(_)),
+        Rule::Bstr => matches!(value, Value::Bytes(_)),
+        Rule::Bool => matches!(value, Value::Bool(_)),
+        Rule::Float => matches!(value, Value::Float(_)),
+        Rule::Array(element) => match value {
+            Value::Array(items) => {
+                for item in items {
+                    matches_rule(element, item)?;
+                }
+                true
+            }
+            _ => false,
+        },
+        Rule::Map(key_rule, value_rule) => match value {
+            Value::Map(entries) => {
+                for (k, v) in entries {
+                    matches_rule(key_rule, k)?;
+                    matches_rule(value_rule, v)?;
+                }
+                true
+            }
+            _ => false,
+        },
+        Rule::Choice(options) => {
+            return options
+                .iter()
+                .find_map(|option| matches_rule(option, value).ok())
+                .ok_or_else(|| CddlError::Mismatch { reason: format!("{:?} matches none of the choice alternatives", value) });
+        }
+        Rule::Size(inner, size) => {
+            matches_rule(inner, value)?;
+            size_matches(value, *size)
+        }
+        Rule::Range(inner, min, max) => {
+            matches_rule(inner, value)?;
+            match value {
+                Value::Uint(n) => (*n as i64) >= *min && (*n as i64) <= *max,
+                Value::Nint(n) => *n >= *min && *n <= *max,
+                _ => false,
+            }
+        }
+    };
+
+    if ok {
+        Ok(())
+    } else {
+        Err(CddlError::Mismatch { reason: format!("{:?} does not satisfy {:?}", value, rule) })
+    }
+}
+
+/// Checks the `.size` control: for a string it is a byte length, and for an
+/// integer it is the number of bytes its value must encode within (e.g.
+/// `uint .size 4` accepts any value that fits in a 4-byte unsigned integer).
+fn size_matches(value: &Value, size: u64) -> bool {
+    match value {
+        Value::Bytes(bytes) => bytes.len() as u64 == size,
+        Value::Text(text) => text.len() as u64 == size,
+        Value::Uint(n) => match size.checked_mul(8) {
+            Some(bits) if bits < 64 => *n < (1u64 << bits),
+            _ => true,
+        },
+        Value::Nint(n) => match size {
+            0 => false,
+            _ => match size.checked_mul(8) {
+                Some(bits) if bits < 64 => *n >= -(1i64 << (bits - 1)),
+                _ => true,
+            },
+        },
+        _ => false,
+    }
+}
+
+/// Parses the subset of CDDL described in the module docs.
+fn parse_rule(input: &str) -> Result<Rule, CddlError> {
+    let trimmed = input.trim();
+    let err = |reason: &str| CddlError::Parse { rule: input.to_string(), reason: reason.to_string() };
+
+    // Choice: `a / b / c`, lowest precedence, split on top-level `/`.
+    if let Some(alternatives) = split_top_level(trimmed, '/') {
+        let rules = alternatives
+            .iter()
+            .map(|alt| parse_rule(alt))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(Rule::Choice(rules));
+    }
+
+    // Control operators: `<target> .size <n>` and `<target> <min>..<max>`.
+    if let Some(idx) = trimmed.find(".size") {
+        let (target, rest) = trimmed.split_at(idx);
+        let size_str = rest.trim_start_matches(".size").trim();
+        let size: u64 = size_str.parse().map_err(|_| err("expected an integer after .size"))?;
+        return Ok(Rule::Size(Box::new(parse_rule(target)?), size));
+    }
+    if let Some(idx) = trimmed.find("..") {
+        let (target, rest) = trimmed.split_at(idx);
+        let target = target.trim();
+        // `target` here is itself the lower bound when the base type is bare,
+        // e.g. `0..100` parses as the integer range over `int`.
+        let bounds = &rest[2..];
+        let mut parts = bounds.split_whitespace();
+        let max_str = parts.next().ok_or_else(|| err("expected an upper bound after .."))?;
+        let min: i64 = target.parse().map_err(|_| err("expected an integer lower bound"))?;
+        let max: i64 = max_str.parse().map_err(|_| err("expected an integer upper bound"))?;
+        return Ok(Rule::Range(Box::new(Rule::Int), min, max));
+    }
+
+    if let Some(inner) = trimmed.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        let inner = inner.trim();
+        let inner = inner.strip_prefix('*').map(|s| s.trim()).unwrap_or(inner);
+        return Ok(Rule::Array(Box::new(parse_rule(inner)?)));
+    }
+
+    if let Some(inner) = trimmed.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+        let inner = inner.trim();
+        let (key, value) = inner
+            .split_once("=>")
+            .ok_or_else(|| err("expected `key => value` inside map"))?;
+        return Ok(Rule::Map(Box::new(parse_rule(key)?), Box::new(parse_rule(value)?)));
+    }
+
+    match trimmed {
+        "uint" => Ok(Rule::Uint),
+        "int" => Ok(Rule::Int),
+        "tstr" | "text" => Ok(Rule::Tstr),
+        "bstr" | "bytes" => Ok(Rule::Bstr),
+        "bool" => Ok(Rule::Bool),
+        "float" => Ok(Rule::Float),
+        other => Err(err(&format!("unknown or unsupported CDDL type `{}`", other))),
+    }
+}
+
+/// Splits `input` on a top-level occurrence of `sep`, ignoring separators
+/// nested inside `[...]` or `{...}`. Returns `None` if `sep` never occurs
+/// at the top level.
+fn split_top_level(input: &str, sep: char) -> Option<Vec<&str>> {
+    let mut depth = 0i32;
+    let mut parts = Vec::new();
+    let mut last = 0;
+    let mut found = false;
+
+    for (idx, ch) in input.char_indices() {
+        match ch {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            c if c == sep && depth == 0 => {
+                parts.push(input[last..idx].trim());
+                last = idx + c.len_utf8();
+                found = true;
+            }
+            _ => {}
+        }
+    }
+
+    if !found {
+        return None;
+    }
+    parts.push(input[last..].trim());
+    Some(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uint_rule_matches_uint() {
+        let rule = parse_rule("uint").unwrap();
+        assert!(matches_rule(&rule, &Value::Uint(42)).is_ok());
+        assert!(matches_rule(&rule, &Value::Text("42".into())).is_err());
+    }
+
+    #[test]
+    fn array_rule_matches_element_type() {
+        let rule = parse_rule("[* uint]").unwrap();
+        assert!(matches_rule(&rule, &Value::Array(vec![Value::Uint(1), Value::Uint(2)])).is_ok());
+        assert!(matches_rule(&rule, &Value::Array(vec![Value::Uint(1), Value::Text("x".into())])).is_err());
+    }
+
+    #[test]
+    fn map_rule_matches_key_and_value_types() {
+        let rule = parse_rule("{ tstr => uint }").unwrap();
+        let value = Value::Map(vec![(Value::Text("a".into()), Value::Uint(1))]);
+        assert!(matches_rule(&rule, &value).is_ok());
+    }
+
+    #[test]
+    fn choice_rule_matches_any_alternative() {
+        let rule = parse_rule("uint / tstr").unwrap();
+        assert!(matches_rule(&rule, &Value::Uint(1)).is_ok());
+        assert!(matches_rule(&rule, &Value::Text("a".into())).is_ok());
+        assert!(matches_rule(&rule, &Value::Bool(true)).is_err());
+    }
+
+    #[test]
+    fn size_control_checks_byte_length() {
+        let rule = parse_rule("bstr .size 4").unwrap();
+        assert!(matches_rule(&rule, &Value::Bytes(vec![0; 4])).is_ok());
+        assert!(matches_rule(&rule, &Value::Bytes(vec![0; 3])).is_err());
+    }
+
+    #[test]
+    fn size_control_checks_integer_byte_width() {
+        let rule = parse_rule("uint .size 4").unwrap();
+        assert!(matches_rule(&rule, &Value::Uint(0xffff_ffff)).is_ok());
+        assert!(matches_rule(&rule, &Value::Uint(0x1_0000_0000)).is_err());
+    }
+
+    #[test]
+    fn range_control_checks_bounds() {
+        let rule = parse_rule("0..100").unwrap();
+        assert!(matches_rule(&rule, &Value::Uint(50)).is_ok());
+        assert!(matches_rule(&rule, &Value::Uint(150)).is_err());
+    }
+
+    #[test]
+    fn decode_builds_matching_value_tree() {
+        assert_eq!(decode(&[0x01]).unwrap(), Value::Uint(1));
+        assert_eq!(decode(&[0x63, b'a', b'b', b'c']).unwrap(), Value::Text("abc".into()));
+    }
+
+    #[test]
+    fn decode_rejects_a_negative_integer_that_does_not_fit_in_an_i64() {
+        // CBOR: -2^64, encoded as negint(0xffffffffffffffff)
+        let data = [0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        assert!(matches!(decode(&data), Err(CddlError::Decode { .. })));
+    }
+
+    #[test]
+    fn schemas_validate_by_property() {
+        let mut schemas = CddlSchemas::new();
+        schemas.register(b"age".to_vec(), "uint").unwrap();
+
+        assert!(schemas.validate(b"age", &Value::Uint(30)).is_ok());
+        assert!(schemas.validate(b"age", &Value::Text("thirty".into())).is_err());
+        assert!(matches!(schemas.validate(b"height", &Value::Uint(1)), Err(CddlError::UnknownProperty)));
+    }
+}
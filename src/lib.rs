@@ -0,0 +1,5 @@
+pub mod cbor_walk;
+pub mod cddl;
+pub mod serialization_data;
+pub mod stream_validate;
+pub mod transcode;
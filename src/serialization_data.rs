@@ -2,48 +2,97 @@ use rlay_ontology::prelude::*;
 use multicodec::{MultiCodec, Codec};
 use snafu::Snafu;
 
+use crate::cbor_walk::{self, Limit, Limits};
+use crate::cddl::{self, CddlSchemas};
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Could not parse data value as Multicodec: {}", cause))]
     MulticodecParseError{ cause: multicodec::Error },
     #[snafu(display("Unsupported codec for data value: {:?}", codec))]
     UnsupportedCodec { codec: multicodec::Codec },
-    #[snafu(display("Undecodable CBOR: {:?}", cause))]
-    UndecodableCbor { cause: cbor::CborError },
+    #[snafu(display("Undecodable CBOR at byte {}: {}", cause.offset, cause.reason))]
+    UndecodableCbor { cause: cbor_walk::Violation },
+    #[snafu(display("Undecodable JSON: {}", cause))]
+    UndecodableJson { cause: serde_json::Error },
+    #[snafu(display("Non-canonical CBOR at byte {}: {}", reason.offset, reason.reason))]
+    NonCanonicalCbor { reason: cbor_walk::Violation },
+    #[snafu(display("Data value does not conform to its property's CDDL schema: {}", cause))]
+    SchemaViolation { cause: cddl::CddlError },
+    #[snafu(display("CBOR data exceeded the {:?} limit", limit))]
+    LimitExceeded { limit: Limit },
 }
 
 /// Checks that the data fields of an entity are in the proper format
 /// of a Multicodec encoded serialization format (CBOR, JSON, etc.), and that
 /// the encoded data is deserializable.
 ///
-/// Currently only supports CBOR.
-#[derive(Debug, Default)]
-pub struct SerializationFormatDataFields;
+/// Supports CBOR, as well as plain JSON and DAG-JSON.
+///
+/// In [`strict`](SerializationFormatDataFields::strict) mode, CBOR data is
+/// additionally required to use the RFC 8949 "preferred serialization"
+/// (shortest-form) encoding rules, since `rlay` entities are
+/// content-addressed and a non-deterministic encoding would let the same
+/// logical value hash to more than one CID.
+///
+/// CBOR input is always walked incrementally against conservative
+/// [`Limits`](cbor_walk::Limits) (see [`with_limits`](SerializationFormatDataFields::with_limits)),
+/// so a hostile blob can't exhaust memory or the stack before validation
+/// gets a chance to reject it.
+#[derive(Debug)]
+pub struct SerializationFormatDataFields {
+    strict: bool,
+    schemas: Option<CddlSchemas>,
+    limits: Limits,
+}
+
+impl Default for SerializationFormatDataFields {
+    fn default() -> Self {
+        SerializationFormatDataFields { strict: false, schemas: None, limits: Limits::default() }
+    }
+}
 
 impl SerializationFormatDataFields {
+    /// Builds a validator that additionally enforces canonical CBOR encoding.
+    pub fn strict() -> Self {
+        SerializationFormatDataFields { strict: true, ..Self::default() }
+    }
+
+    /// Builds a validator that additionally checks decoded CBOR values
+    /// against the CDDL rule registered for their property.
+    pub fn with_schemas(schemas: CddlSchemas) -> Self {
+        SerializationFormatDataFields { schemas: Some(schemas), ..Self::default() }
+    }
+
+    /// Builds a validator with custom resource limits for untrusted CBOR
+    /// input, overriding the conservative defaults.
+    pub fn with_limits(limits: Limits) -> Self {
+        SerializationFormatDataFields { limits, ..Self::default() }
+    }
+
     pub fn validate(&self, entity: &Entity) -> Result<(), Error> {
         match entity {
             Entity::Annotation(entity) => {
-                Self::validate_field(&entity.value)?;
+                self.validate_field(Some(&entity.property), &entity.value)?;
             }
             Entity::DataPropertyAssertion(entity) => {
                 if let Some(ref value) = entity.target {
-                    Self::validate_field(value)?;
+                    self.validate_field(entity.property.as_deref(), value)?;
                 }
             }
             Entity::NegativeDataPropertyAssertion(entity) => {
                 if let Some(ref value) = entity.target {
-                    Self::validate_field(value)?;
+                    self.validate_field(entity.property.as_deref(), value)?;
                 }
             }
             Entity::AnnotationAssertion(entity) => {
                 if let Some(ref value) = entity.value {
-                    Self::validate_field(value)?;
+                    self.validate_field(entity.property.as_deref(), value)?;
                 }
             }
             Entity::NegativeAnnotationAssertion(entity) => {
                 if let Some(ref value) = entity.value {
-                    Self::validate_field(value)?;
+                    self.validate_field(entity.property.as_deref(), value)?;
                 }
             }
             _ => (),
@@ -52,18 +101,41 @@ impl SerializationFormatDataFields {
         Ok(())
     }
 
-    fn validate_field(data: &[u8]) -> Result<(), Error> {
+    fn validate_field(&self, property: Option<&[u8]>, data: &[u8]) -> Result<(), Error> {
         let parsed = MultiCodec::from(data).map_err(|e| Error::MulticodecParseError{ cause: e })?;
         match parsed.codec {
-            Codec::Cbor => Self::validate_cbor_value(parsed.data),
+            Codec::Cbor => self.validate_cbor_value(property, parsed.data),
+            Codec::Json | Codec::DagJson => Self::validate_json_value(parsed.data),
             other => Err(Error::UnsupportedCodec { codec: other }),
         }?;
 
         Ok(())
     }
 
-    fn validate_cbor_value(data: &[u8]) -> Result<(), Error> {
-        let _: Vec<cbor::Cbor> = cbor::Decoder::from_bytes(data).items().collect::<Result<_, _>>().map_err(|e| Error::UndecodableCbor { cause: e })?;
+    fn validate_cbor_value(&self, property: Option<&[u8]>, data: &[u8]) -> Result<(), Error> {
+        cbor_walk::walk(data, &self.limits, self.strict).map_err(|err| match err {
+            cbor_walk::WalkError::Malformed(cause) => Error::UndecodableCbor { cause },
+            cbor_walk::WalkError::NonCanonical(reason) => Error::NonCanonicalCbor { reason },
+            cbor_walk::WalkError::LimitExceeded(limit) => Error::LimitExceeded { limit },
+        })?;
+
+        // The CDDL schema lookup is keyed by property, so there is nothing
+        // to check it against when the entity doesn't carry one - but the
+        // syntactic/canonical/limit checks above still ran regardless.
+        if let (Some(schemas), Some(property)) = (&self.schemas, property) {
+            let value = cddl::decode(data).map_err(|cause| Error::SchemaViolation { cause })?;
+            // Properties without a registered schema are unconstrained.
+            match schemas.validate(property, &value) {
+                Ok(()) | Err(cddl::CddlError::UnknownProperty) => {}
+                Err(cause) => return Err(Error::SchemaViolation { cause }),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_json_value(data: &[u8]) -> Result<(), Error> {
+        let _: serde_json::Value = serde_json::from_slice(data).map_err(|e| Error::UndecodableJson { cause: e })?;
         Ok(())
     }
 }
@@ -111,4 +183,123 @@ mod tests {
         let validator = SerializationFormatDataFields::default();
         assert!(validator.validate(&entity).is_err());
     }
+
+    #[test]
+    fn simple_annotation_json() {
+        let entity: Entity = Annotation {
+            annotations: vec![],
+            property: vec![],
+            value: MultiCodec::new(Codec::Json, &br#"{"foo":"bar"}"#.to_vec()).pack(),
+        }.into();
+
+        let validator = SerializationFormatDataFields::default();
+        assert!(validator.validate(&entity).is_ok());
+    }
+
+    #[test]
+    fn simple_annotation_dag_json() {
+        let entity: Entity = Annotation {
+            annotations: vec![],
+            property: vec![],
+            value: MultiCodec::new(Codec::DagJson, &br#"[1,2,3]"#.to_vec()).pack(),
+        }.into();
+
+        let validator = SerializationFormatDataFields::default();
+        assert!(validator.validate(&entity).is_ok());
+    }
+
+    #[test]
+    fn simple_annotation_json_undecodable() {
+        let entity: Entity = Annotation {
+            annotations: vec![],
+            property: vec![],
+            value: MultiCodec::new(Codec::Json, &b"{not valid json".to_vec()).pack(),
+        }.into();
+
+        let validator = SerializationFormatDataFields::default();
+        assert!(validator.validate(&entity).is_err());
+    }
+
+    #[test]
+    fn strict_rejects_non_canonical_cbor() {
+        let entity: Entity = Annotation {
+            annotations: vec![],
+            property: vec![],
+            // CBOR: 0 encoded as a 1-byte-follows unsigned int instead of directly
+            value: MultiCodec::new(Codec::Cbor, &hex!("1800").to_vec()).pack(),
+        }.into();
+
+        assert!(SerializationFormatDataFields::default().validate(&entity).is_ok());
+        assert!(SerializationFormatDataFields::strict().validate(&entity).is_err());
+    }
+
+    #[test]
+    fn schema_rejects_value_of_wrong_type() {
+        let mut schemas = crate::cddl::CddlSchemas::new();
+        schemas.register(b"age".to_vec(), "uint").unwrap();
+        let validator = SerializationFormatDataFields::with_schemas(schemas);
+
+        let valid: Entity = Annotation {
+            annotations: vec![],
+            property: b"age".to_vec(),
+            // CBOR: 30
+            value: MultiCodec::new(Codec::Cbor, &hex!("181e").to_vec()).pack(),
+        }.into();
+        assert!(validator.validate(&valid).is_ok());
+
+        let invalid: Entity = Annotation {
+            annotations: vec![],
+            property: b"age".to_vec(),
+            // CBOR: "thirty"
+            value: MultiCodec::new(Codec::Cbor, &hex!("66746869727479").to_vec()).pack(),
+        }.into();
+        assert!(validator.validate(&invalid).is_err());
+    }
+
+    #[test]
+    fn limits_reject_oversized_declared_array() {
+        let entity: Entity = Annotation {
+            annotations: vec![],
+            property: vec![],
+            // CBOR: array(1000), no payload
+            value: MultiCodec::new(Codec::Cbor, &hex!("9903e8").to_vec()).pack(),
+        }.into();
+
+        let validator = SerializationFormatDataFields::with_limits(crate::cbor_walk::Limits {
+            max_collection_items: 10,
+            ..Default::default()
+        });
+        assert!(matches!(validator.validate(&entity), Err(Error::LimitExceeded { .. })));
+    }
+
+    #[test]
+    fn data_property_assertion_without_a_property_still_gets_syntax_checked() {
+        let entity: Entity = DataPropertyAssertion {
+            annotations: vec![],
+            subject: None,
+            property: None,
+            // CBOR: undecodable data
+            target: Some(MultiCodec::new(Codec::Cbor, &hex!("f9").to_vec()).pack()),
+        }.into();
+
+        let validator = SerializationFormatDataFields::default();
+        assert!(validator.validate(&entity).is_err());
+    }
+
+    #[test]
+    fn data_property_assertion_without_a_property_skips_schema_check() {
+        let mut schemas = crate::cddl::CddlSchemas::new();
+        schemas.register(b"age".to_vec(), "uint").unwrap();
+        let validator = SerializationFormatDataFields::with_schemas(schemas);
+
+        let entity: Entity = DataPropertyAssertion {
+            annotations: vec![],
+            subject: None,
+            property: None,
+            // CBOR: "thirty" - would fail the "age" schema, but there's no property to look it up by
+            target: Some(MultiCodec::new(Codec::Cbor, &hex!("66746869727479").to_vec()).pack()),
+        }.into();
+
+        assert!(validator.validate(&entity).is_ok());
+    }
 }
@@ -0,0 +1,197 @@
+//! Async, back-pressured validation of entity batches.
+//!
+//! The rest of this crate validates one fully-owned `&Entity` at a time,
+//! which is fine for request/response use but means an ingestion pipeline
+//! reading entities off a socket or a large file has to buffer everything
+//! first. [`EntityValidationCodec`] is a `tokio_util` framing
+//! [`Decoder`](tokio_util::codec::Decoder), analogous to `futures_cbor_codec`,
+//! that frames length-prefixed, Protobuf-encoded entities off an `AsyncRead`
+//! and validates each one's data fields as it comes off the wire. Wrapping
+//! it in a `FramedRead` turns any `AsyncRead` into a
+//! `Stream<Item = Result<Entity, Error>>`. The length prefix is
+//! attacker-controlled, so it is checked against a `max_frame_length` before
+//! any buffer space is reserved for it, the same way
+//! `tokio_util::codec::LengthDelimitedCodec` guards its own length prefix.
+
+use bytes::{Buf, BytesMut};
+use futures::Stream;
+use prost::Message;
+use rlay_ontology::prelude::Entity;
+use tokio::io::AsyncRead;
+use tokio_util::codec::{Decoder, FramedRead};
+
+use crate::serialization_data::{Error as ValidationError, SerializationFormatDataFields};
+
+/// The length, in bytes, of the big-endian frame length prefix.
+const LENGTH_PREFIX_BYTES: usize = 4;
+
+/// The default cap on a single frame's declared length, matching
+/// `tokio_util::codec::LengthDelimitedCodec`'s default.
+const DEFAULT_MAX_FRAME_LENGTH: usize = 8 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Decode(prost::DecodeError),
+    Validation(ValidationError),
+    /// The frame length prefix declared more bytes than `max_frame_length`
+    /// allows, so the frame was rejected before any buffer space was
+    /// reserved for it.
+    FrameTooLong { declared: usize, max: usize },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error while reading entity frame: {}", e),
+            Error::Decode(e) => write!(f, "could not decode entity Protobuf frame: {}", e),
+            Error::Validation(e) => write!(f, "entity failed data field validation: {}", e),
+            Error::FrameTooLong { declared, max } => {
+                write!(f, "entity frame declared length {} exceeds the {} byte limit", declared, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// A `tokio_util::codec::Decoder` that frames `[u32 big-endian length][entity bytes]`
+/// records off a byte stream and validates each decoded entity's data
+/// fields with a [`SerializationFormatDataFields`] validator.
+pub struct EntityValidationCodec {
+    validator: SerializationFormatDataFields,
+    max_frame_length: usize,
+}
+
+impl EntityValidationCodec {
+    pub fn new(validator: SerializationFormatDataFields) -> Self {
+        EntityValidationCodec { validator, max_frame_length: DEFAULT_MAX_FRAME_LENGTH }
+    }
+
+    /// Builds a codec that rejects any frame whose declared length exceeds
+    /// `max_frame_length`, instead of the `DEFAULT_MAX_FRAME_LENGTH` default.
+    pub fn with_max_frame_length(validator: SerializationFormatDataFields, max_frame_length: usize) -> Self {
+        EntityValidationCodec { validator, max_frame_length }
+    }
+}
+
+impl Default for EntityValidationCodec {
+    fn default() -> Self {
+        EntityValidationCodec::new(SerializationFormatDataFields::default())
+    }
+}
+
+impl Decoder for EntityValidationCodec {
+    type Item = Entity;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Entity>, Error> {
+        if src.len() < LENGTH_PREFIX_BYTES {
+            return Ok(None);
+        }
+
+        let len = u32::from_be_bytes(src[..LENGTH_PREFIX_BYTES].try_into().expect("checked length above")) as usize;
+        if len > self.max_frame_length {
+            return Err(Error::FrameTooLong { declared: len, max: self.max_frame_length });
+        }
+        if src.len() < LENGTH_PREFIX_BYTES + len {
+            src.reserve(LENGTH_PREFIX_BYTES + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(LENGTH_PREFIX_BYTES);
+        let frame = src.split_to(len);
+
+        let entity = Entity::decode(&frame[..]).map_err(Error::Decode)?;
+        self.validator.validate(&entity).map_err(Error::Validation)?;
+        Ok(Some(entity))
+    }
+}
+
+/// Wraps `reader` in [`EntityValidationCodec`] and returns a
+/// `Stream<Item = Result<Entity, Error>>` of validated entities, reading
+/// (and validating) only as much of `reader` as the consumer pulls.
+pub fn validate_stream<R>(reader: R, validator: SerializationFormatDataFields) -> impl Stream<Item = Result<Entity, Error>>
+where
+    R: AsyncRead + Unpin,
+{
+    FramedRead::new(reader, EntityValidationCodec::new(validator))
+}
+
+/// Like [`validate_stream`], but rejects any frame whose declared length
+/// exceeds `max_frame_length` rather than buffering up to the
+/// `DEFAULT_MAX_FRAME_LENGTH` default.
+pub fn validate_stream_with_max_frame_length<R>(
+    reader: R,
+    validator: SerializationFormatDataFields,
+    max_frame_length: usize,
+) -> impl Stream<Item = Result<Entity, Error>>
+where
+    R: AsyncRead + Unpin,
+{
+    FramedRead::new(reader, EntityValidationCodec::with_max_frame_length(validator, max_frame_length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use multicodec::{Codec, MultiCodec};
+    use rlay_ontology::prelude::Annotation;
+
+    fn framed_entity(entity: &Entity) -> Vec<u8> {
+        let mut payload = Vec::new();
+        entity.encode(&mut payload).expect("encoding to a Vec cannot fail");
+        let mut framed = (payload.len() as u32).to_be_bytes().to_vec();
+        framed.extend_from_slice(&payload);
+        framed
+    }
+
+    #[tokio::test]
+    async fn validates_entities_as_they_are_decoded() {
+        let entity: Entity = Annotation {
+            annotations: vec![],
+            property: vec![],
+            value: MultiCodec::new(Codec::Cbor, &vec![0xf5]).pack(),
+        }
+        .into();
+
+        let bytes = framed_entity(&entity);
+        let mut stream = validate_stream(bytes.as_slice(), SerializationFormatDataFields::default());
+
+        let decoded = stream.next().await.unwrap().unwrap();
+        assert_eq!(decoded, entity);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn surfaces_validation_errors_as_stream_items() {
+        let entity: Entity = Annotation {
+            annotations: vec![],
+            property: vec![],
+            // wrong codec for the data field
+            value: MultiCodec::new(Codec::Protobuf, &vec![0xf5]).pack(),
+        }
+        .into();
+
+        let bytes = framed_entity(&entity);
+        let mut stream = validate_stream(bytes.as_slice(), SerializationFormatDataFields::default());
+
+        assert!(matches!(stream.next().await, Some(Err(Error::Validation(_)))));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_frame_whose_declared_length_exceeds_the_configured_max() {
+        // A declared length of 1000 bytes, no payload supplied.
+        let bytes = 1000u32.to_be_bytes().to_vec();
+        let mut stream = validate_stream_with_max_frame_length(bytes.as_slice(), SerializationFormatDataFields::default(), 16);
+
+        assert!(matches!(stream.next().await, Some(Err(Error::FrameTooLong { declared: 1000, max: 16 }))));
+    }
+}
@@ -0,0 +1,257 @@
+//! Transcoding between the CBOR a data field is stored as and
+//! human-readable JSON, for tooling and debugging.
+//!
+//! Byte strings have no JSON equivalent, so they are rendered through the
+//! same escaping convention used elsewhere in IPLD tooling:
+//! `{ "/": { "bytes": "<base64>" } }`. A plain JSON object that happens to
+//! look like that is therefore always interpreted as a byte string on the
+//! way back to CBOR, which is what makes byte strings reversible.
+//!
+//! Not everything survives the trip: CBOR tags are dropped (only the tagged
+//! value is rendered), non-finite floats are rendered as JSON `null`, and a
+//! map that repeats a key keeps only its last entry.
+
+use multicodec::{Codec, MultiCodec};
+
+use crate::cddl::Value;
+
+#[derive(Debug)]
+pub enum TranscodeError {
+    Multicodec(multicodec::Error),
+    UnsupportedCodec(multicodec::Codec),
+    Decode(crate::cddl::CddlError),
+    Json(String),
+    InvalidBytesEscape(String),
+    NonStringMapKey,
+}
+
+impl std::fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranscodeError::Multicodec(e) => write!(f, "could not parse data value as Multicodec: {}", e),
+            TranscodeError::UnsupportedCodec(codec) => write!(f, "unsupported codec for transcoding: {:?}", codec),
+            TranscodeError::Decode(e) => write!(f, "could not decode CBOR value: {}", e),
+            TranscodeError::Json(e) => write!(f, "invalid JSON: {}", e),
+            TranscodeError::InvalidBytesEscape(reason) => write!(f, "invalid `{{\"/\": {{\"bytes\": ...}}}}` escape: {}", reason),
+            TranscodeError::NonStringMapKey => write!(f, "CBOR map has a non-string key, which has no JSON equivalent"),
+        }
+    }
+}
+
+impl std::error::Error for TranscodeError {}
+
+/// Takes a Multicodec-framed CBOR data field and renders it as a
+/// human-readable JSON string.
+pub fn cbor_to_json(data: &[u8]) -> Result<String, TranscodeError> {
+    let parsed = MultiCodec::from(data).map_err(TranscodeError::Multicodec)?;
+    if parsed.codec != Codec::Cbor {
+        return Err(TranscodeError::UnsupportedCodec(parsed.codec));
+    }
+
+    let value = crate::cddl::decode(parsed.data).map_err(TranscodeError::Decode)?;
+    let json = value_to_json(&value)?;
+    serde_json::to_string(&json).map_err(|e| TranscodeError::Json(e.to_string()))
+}
+
+/// Parses a JSON string produced by [`cbor_to_json`] (or written by hand
+/// following the same escaping convention) and returns Multicodec-framed
+/// CBOR bytes ready to store in a data field.
+pub fn json_to_cbor(json: &str) -> Result<Vec<u8>, TranscodeError> {
+    let parsed: serde_json::Value = serde_json::from_str(json).map_err(|e| TranscodeError::Json(e.to_string()))?;
+    let value = json_to_value(&parsed)?;
+
+    let mut bytes = Vec::new();
+    encode_value(&value, &mut bytes);
+    Ok(MultiCodec::new(Codec::Cbor, &bytes).pack())
+}
+
+fn value_to_json(value: &Value) -> Result<serde_json::Value, TranscodeError> {
+    use serde_json::Value as Json;
+
+    Ok(match value {
+        Value::Uint(n) => Json::from(*n),
+        Value::Nint(n) => Json::from(*n),
+        Value::Bytes(bytes) => {
+            let mut escape = serde_json::Map::new();
+            escape.insert("bytes".to_string(), Json::from(base64::encode(bytes)));
+            let mut wrapper = serde_json::Map::new();
+            wrapper.insert("/".to_string(), Json::Object(escape));
+            Json::Object(wrapper)
+        }
+        Value::Text(text) => Json::from(text.clone()),
+        Value::Array(items) => {
+            let items = items.iter().map(value_to_json).collect::<Result<Vec<_>, _>>()?;
+            Json::Array(items)
+        }
+        Value::Map(entries) => {
+            let mut object = serde_json::Map::new();
+            for (key, value) in entries {
+                let key = match key {
+                    Value::Text(text) => text.clone(),
+                    _ => return Err(TranscodeError::NonStringMapKey),
+                };
+                object.insert(key, value_to_json(value)?);
+            }
+            Json::Object(object)
+        }
+        Value::Bool(b) => Json::from(*b),
+        Value::Null => Json::Null,
+        Value::Float(f) => serde_json::Number::from_f64(*f).map(Json::Number).unwrap_or(Json::Null),
+    })
+}
+
+fn json_to_value(json: &serde_json::Value) -> Result<Value, TranscodeError> {
+    use serde_json::Value as Json;
+
+    Ok(match json {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Bool(*b),
+        Json::Number(n) => {
+            if let Some(u) = n.as_u64() {
+                Value::Uint(u)
+            } else if let Some(i) = n.as_i64() {
+                Value::Nint(i)
+            } else {
+                Value::Float(n.as_f64().unwrap_or_default())
+            }
+        }
+        Json::String(s) => Value::Text(s.clone()),
+        Json::Array(items) => Value::Array(items.iter().map(json_to_value).collect::<Result<Vec<_>, _>>()?),
+        Json::Object(map) => {
+            if let Some(bytes) = as_bytes_escape(map)? {
+                return Ok(Value::Bytes(bytes));
+            }
+            let mut entries = map
+                .iter()
+                .map(|(k, v)| Ok((Value::Text(k.clone()), json_to_value(v)?)))
+                .collect::<Result<Vec<_>, TranscodeError>>()?;
+            // Keep the resulting CBOR map in canonical, bytewise-sorted key order.
+            entries.sort_by(|(a, _), (b, _)| {
+                let mut a_bytes = Vec::new();
+                let mut b_bytes = Vec::new();
+                encode_value(a, &mut a_bytes);
+                encode_value(b, &mut b_bytes);
+                a_bytes.cmp(&b_bytes)
+            });
+            Value::Map(entries)
+        }
+    })
+}
+
+/// Recognizes the `{ "/": { "bytes": "<base64>" } }` escape convention.
+fn as_bytes_escape(map: &serde_json::Map<String, serde_json::Value>) -> Result<Option<Vec<u8>>, TranscodeError> {
+    if map.len() != 1 {
+        return Ok(None);
+    }
+    let Some(serde_json::Value::Object(inner)) = map.get("/") else {
+        return Ok(None);
+    };
+    if inner.len() != 1 {
+        return Ok(None);
+    }
+    let Some(serde_json::Value::String(encoded)) = inner.get("bytes") else {
+        return Ok(None);
+    };
+    let bytes = base64::decode(encoded).map_err(|e| TranscodeError::InvalidBytesEscape(e.to_string()))?;
+    Ok(Some(bytes))
+}
+
+fn encode_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Uint(n) => write_head(out, 0, *n),
+        Value::Nint(n) => write_head(out, 1, (-1 - *n) as u64),
+        Value::Bytes(bytes) => {
+            write_head(out, 2, bytes.len() as u64);
+            out.extend_from_slice(bytes);
+        }
+        Value::Text(text) => {
+            write_head(out, 3, text.len() as u64);
+            out.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(out, 4, items.len() as u64);
+            for item in items {
+                encode_value(item, out);
+            }
+        }
+        Value::Map(entries) => {
+            write_head(out, 5, entries.len() as u64);
+            for (key, value) in entries {
+                encode_value(key, out);
+                encode_value(value, out);
+            }
+        }
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Null => out.push(0xf6),
+        Value::Float(f) => {
+            out.push(0xfb);
+            out.extend_from_slice(&f.to_be_bytes());
+        }
+    }
+}
+
+fn write_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let head = major << 5;
+    if value < 24 {
+        out.push(head | value as u8);
+    } else if value <= u8::MAX as u64 {
+        out.push(head | 24);
+        out.push(value as u8);
+    } else if value <= u16::MAX as u64 {
+        out.push(head | 25);
+        out.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(head | 26);
+        out.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        out.push(head | 27);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn roundtrips_plain_values() {
+        let original = r#"{"age":30,"name":"ada"}"#;
+        let cbor = json_to_cbor(original).unwrap();
+        let json = cbor_to_json(&cbor).unwrap();
+        let reparsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let expected: serde_json::Value = serde_json::from_str(original).unwrap();
+        assert_eq!(reparsed, expected);
+    }
+
+    #[test]
+    fn roundtrips_byte_strings_through_the_escape_convention() {
+        let original = r#"{"/":{"bytes":"AQIDBA=="}}"#;
+        let cbor = json_to_cbor(original).unwrap();
+        let json = cbor_to_json(&cbor).unwrap();
+        assert_eq!(json, original);
+    }
+
+    #[test]
+    fn rejects_non_cbor_codec() {
+        let data = MultiCodec::new(Codec::Json, &br#"{}"#.to_vec()).pack();
+        assert!(matches!(cbor_to_json(&data), Err(TranscodeError::UnsupportedCodec(_))));
+    }
+
+    #[test]
+    fn rejects_a_map_with_a_non_string_key() {
+        // CBOR: {1: true}
+        let data = MultiCodec::new(Codec::Cbor, &hex!("a101f5").to_vec()).pack();
+        assert!(matches!(cbor_to_json(&data), Err(TranscodeError::NonStringMapKey)));
+    }
+
+    #[test]
+    fn rejects_rather_than_silently_corrupting_an_out_of_range_negative_integer() {
+        // CBOR: -2^64, encoded as negint(0xffffffffffffffff), which has no
+        // i64 representation - transcoding this must error, not round-trip
+        // to the wrong number.
+        let data = MultiCodec::new(Codec::Cbor, &hex!("3bffffffffffffffff").to_vec()).pack();
+        assert!(matches!(cbor_to_json(&data), Err(TranscodeError::Decode(_))));
+    }
+}